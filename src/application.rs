@@ -1,20 +1,56 @@
 use clap::Parser;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{self, Read};
 use std::time::Duration;
+use crate::config::Config;
+use crate::error::Error;
 use crate::req::Request;
+use url::{Host, Url};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
 pub enum OutputFormat {
     Raw,
     MarkDown
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone, Copy)]
+pub enum ListFormat {
+    Debug,
+    Table,
+}
+
+#[derive(Clone, Copy)]
+pub enum JsonBody {
+    Minify,
+    Pretty,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
 pub struct TimeoutDuration {
     pub duration: Duration,
 }
 
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RedirectPolicy {
+    None,
+    Limited(usize),
+    Unlimited,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct HostRewrite {
+    pub from: String,
+    pub to: String,
+    pub port: Option<u16>,
+}
+
 #[derive(Parser)]
 #[command(version, author = "Ben Falk <benjamin.falk@yahoo.com>")]
 pub struct Opts {
@@ -25,43 +61,136 @@ pub struct Opts {
     #[clap(long)]
     pub list_requests: bool,
 
+    /// with list-requests, options are 'debug' (default) and 'table'
+    #[clap(long)]
+    format: Option<ListFormat>,
+
+    /// list every variable the input references w/o running requests
+    #[clap(long)]
+    pub list_vars: bool,
+
+    /// report problems with the requests w/o running them
+    #[clap(long)]
+    pub check: bool,
+
+    /// resolve every host and report undefined variables w/o running requests
+    #[clap(long)]
+    pub warmup: bool,
+
+    /// with check, treat warnings as errors
+    #[clap(long)]
+    pub strict: bool,
+
     /// At what line number do you want to run a request
     #[clap(long)]
     pub line: Option<u32>,
 
-    /// options are 'raw' and 'markdown'
-    #[clap(long, default_value = "raw")]
-    pub output: OutputFormat,
+    /// options are 'raw' (default) and 'markdown'
+    #[clap(long)]
+    output: Option<OutputFormat>,
+
+    /// refuse to send any request that isn't https
+    #[clap(long)]
+    pub https_only: bool,
 
     /// optional, examples 15sec 300ms 2min
     #[clap(long)]
     pub timeout: Option<TimeoutDuration>,
+
+    /// optional, only for connecting, same format as timeout
+    #[clap(long)]
+    pub connect_timeout: Option<TimeoutDuration>,
+
+    /// redirects to follow, 'none', 'unlimited' or a number, default 10
+    #[clap(long)]
+    pub redirects: Option<RedirectPolicy>,
+
+    /// times to retry on a connection error or a retry-on status
+    #[clap(long)]
+    pub retries: Option<u32>,
+
+    /// with retries, the statuses to retry on, default 429,502,503,504
+    #[clap(long, value_delimiter = ',')]
+    pub retry_on: Vec<u16>,
+
+    /// reformat JSON bodies before sending, options are 'minify' and 'pretty'
+    #[clap(long)]
+    pub json_body: Option<JsonBody>,
+
+    /// send requests for one host to another, ex: localhost=api.internal
+    #[clap(long)]
+    pub rewrite_host: Vec<HostRewrite>,
+
+    /// append the request and its response to this file as a line of JSON
+    #[clap(long)]
+    pub record: Option<String>,
+
+    /// with list-requests, re-list every time the file changes
+    #[clap(long, requires = "list_requests")]
+    pub watch: bool,
 }
 
-pub fn get_opts() -> Opts {
-    Opts::parse()
+pub fn get_opts() -> Result<Opts, Error> {
+    let mut opts = Opts::parse();
+    opts.apply_config(Config::load()?);
+    Ok(opts)
 }
 
 impl Opts {
-    pub fn input(&self) -> Option<String> {
-        let mut data = String::new();
-        if let Some(filename) = &self.file {
-            let filename = filename.split(":").nth(0)?;
-            let mut file = File::open(filename).unwrap();
-            file.read_to_string(&mut data).unwrap();
-            Some(data)
+    pub fn input(&self) -> Result<String, Error> {
+        if let Some(filename) = self.filename() {
+            Ok(read_input(File::open(filename)?)?)
         } else if !atty::is(atty::Stream::Stdin) {
-            io::stdin().read_to_string(&mut data).unwrap();
-            Some(data)
+            Ok(read_input(io::stdin())?)
         } else {
-            None
+            Err(Error::NoInput)
         }
     }
 
+    pub fn output(&self) -> OutputFormat {
+        self.output.unwrap_or(OutputFormat::Raw)
+    }
+
+    pub fn list_format(&self) -> ListFormat {
+        self.format.unwrap_or(ListFormat::Debug)
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.file_and_line().map(|(filename, _)| filename)
+    }
+
     pub fn apply_overrieds(&self, request: &mut Request) {
         if self.timeout.is_some() {
             request.meta.timeout = self.timeout.clone();
         }
+
+        if self.connect_timeout.is_some() {
+            request.meta.connect_timeout = self.connect_timeout.clone();
+        }
+
+        if self.redirects.is_some() {
+            request.meta.redirects = self.redirects;
+        }
+
+        if let Some(retries) = self.retries {
+            request.meta.retries = retries;
+        }
+
+        if !self.retry_on.is_empty() {
+            request.meta.retry_on = self.retry_on.clone();
+        }
+
+        if let Some(format) = self.json_body {
+            format.apply(request);
+        }
+
+        if let Some(url) = self.rewrite_host.iter().find_map(|r| r.rewrite(&request.host)) {
+            request.host = url.as_str().trim_end_matches('/').to_string();
+        }
+
+        if let Some(url) = self.rewrite_host.iter().find_map(|r| r.rewrite(&request.uri)) {
+            request.uri = url.to_string();
+        }
     }
 
     pub fn at_line(&self) -> Option<u32> {
@@ -69,16 +198,82 @@ impl Opts {
             return self.line;
         }
 
-        self.file
-            .as_ref()
-            .take()?
-            .split(":")
-            .nth(1)?
-            .parse()
-            .map_or(None, |n| Some(n))
+        self.file_and_line()?.1
+    }
+
+    // Private Functions
+
+    /// Fills in anything not given on the command line from the config,
+    /// rewrites from both are kept with the command line's checked first.
+    fn apply_config(&mut self, config: Config) {
+        self.output = self.output.or(config.output);
+
+        if self.timeout.is_none() {
+            self.timeout = config.timeout;
+        }
+
+        if self.connect_timeout.is_none() {
+            self.connect_timeout = config.connect_timeout;
+        }
+
+        self.redirects = self.redirects.or(config.redirects);
+        self.retries = self.retries.or(config.retries);
+
+        if self.retry_on.is_empty() {
+            self.retry_on = config.retry_on;
+        }
+
+        self.rewrite_host.extend(config.rewrite_host);
+        self.https_only |= config.https_only;
+    }
+
+    /// Splits a `file.md:12` argument on its last colon, and only when
+    /// what follows is a line number, so paths with colons in them such
+    /// as `C:\dir\file.md` are left whole.
+    fn file_and_line(&self) -> Option<(&str, Option<u32>)> {
+        let file = self.file.as_ref()?;
+
+        if let Some((filename, line)) = file.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Some((filename, Some(line)));
+            }
+        }
+
+        Some((file, None))
     }
 }
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Reads all of the markdown from `reader`, transparently inflating it
+/// first if it has been gzipped and dropping any leading UTF-8 BOM.  Input
+/// that isn't valid UTF-8 is still read, with a warning, by replacing the
+/// invalid bytes.
+pub fn read_input<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut inflated = vec![];
+        GzDecoder::new(&bytes[..]).read_to_end(&mut inflated)?;
+        bytes = inflated;
+    }
+
+    let mut data = match String::from_utf8(bytes) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("warning: input is not valid UTF-8, invalid bytes were replaced");
+            String::from_utf8_lossy(err.as_bytes()).into_owned()
+        }
+    };
+
+    if data.starts_with('\u{feff}') {
+        data.remove(0);
+    }
+
+    Ok(data)
+}
+
 use std::str::FromStr;
 
 impl FromStr for OutputFormat {
@@ -92,6 +287,48 @@ impl FromStr for OutputFormat {
     }
 }
 
+impl FromStr for ListFormat {
+    type Err = &'static str;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.to_lowercase().as_str() {
+            "debug" => Ok(ListFormat::Debug),
+            "table" => Ok(ListFormat::Table),
+            _ => Err("not a valid list format"),
+        }
+    }
+}
+
+impl FromStr for JsonBody {
+    type Err = &'static str;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.to_lowercase().as_str() {
+            "minify" => Ok(JsonBody::Minify),
+            "pretty" => Ok(JsonBody::Pretty),
+            _ => Err("not a valid json body format"),
+        }
+    }
+}
+
+impl JsonBody {
+    /// Rewrites a JSON body, left alone if the request isn't JSON or the
+    /// body doesn't parse.
+    fn apply(self, request: &mut Request) {
+        if !request.is_json() {
+            return;
+        }
+
+        let data = match request.body.as_deref().map(json::parse) {
+            Some(Ok(data)) => data,
+            _ => return,
+        };
+
+        request.body = Some(match self {
+            JsonBody::Minify => json::stringify(data),
+            JsonBody::Pretty => json::stringify_pretty(data, 2),
+        });
+    }
+}
+
 impl FromStr for TimeoutDuration {
     type Err = &'static str;
     fn from_str(string: &str) -> Result<Self, Self::Err> {
@@ -114,3 +351,242 @@ impl FromStr for TimeoutDuration {
         }
     }
 }
+
+impl FromStr for RedirectPolicy {
+    type Err = &'static str;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.to_lowercase().as_str() {
+            "none" => Ok(RedirectPolicy::None),
+            "unlimited" => Ok(RedirectPolicy::Unlimited),
+            amount => amount
+                .parse()
+                .map(RedirectPolicy::Limited)
+                .map_err(|_| "expected none, unlimited or a number"),
+        }
+    }
+}
+
+impl FromStr for HostRewrite {
+    type Err = &'static str;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (from, to) = match string.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => (from, to),
+            _ => return Err("expected a rewrite like FROM=TO"),
+        };
+
+        // the port is split off of `api.internal:8080`, it can't be set
+        // as part of the host
+        let (to, port) = match to.rsplit_once(':') {
+            Some((host, port)) if Host::parse(host).is_ok() => match port.parse() {
+                Ok(port) => (host, Some(port)),
+                Err(_) => (to, None),
+            },
+            _ => (to, None),
+        };
+
+        if Host::parse(to).is_err() {
+            return Err("expected a rewrite like FROM=TO or FROM=TO:PORT");
+        }
+
+        Ok(Self { from: from.to_string(), to: to.to_string(), port })
+    }
+}
+
+impl TryFrom<String> for OutputFormat {
+    type Error = &'static str;
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        string.parse()
+    }
+}
+
+impl TryFrom<String> for TimeoutDuration {
+    type Error = &'static str;
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        string.parse()
+    }
+}
+
+impl TryFrom<String> for RedirectPolicy {
+    type Error = &'static str;
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        string.parse()
+    }
+}
+
+impl TryFrom<String> for HostRewrite {
+    type Error = &'static str;
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        string.parse()
+    }
+}
+
+impl HostRewrite {
+    /// The url with its host swapped out, if it is a url for this host
+    fn rewrite(&self, url: &str) -> Option<Url> {
+        let mut url = Url::parse(url).ok()?;
+
+        if url.host_str()? != self.from {
+            return None;
+        }
+
+        url.set_host(Some(&self.to)).ok()?;
+
+        if self.port.is_some() {
+            url.set_port(self.port).ok()?;
+        }
+
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{post, request};
+    use clap::Parser;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::try_parse_from(std::iter::once("req_md").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn command_line_wins_over_the_config() {
+        let mut opts = opts(&["--timeout=2min", "--retries=1", "--rewrite-host", "localhost=cli.internal"]);
+        opts.apply_config(toml::from_str(r#"
+            timeout = "15sec"
+            connect_timeout = "500ms"
+            retries = 3
+            rewrite_host = ["localhost=config.internal", "db=db.internal"]
+        "#).unwrap());
+
+        assert_eq!(opts.timeout.map(|t| t.duration), Some(Duration::from_secs(120)));
+        assert_eq!(opts.connect_timeout.map(|t| t.duration), Some(Duration::from_millis(500)));
+        assert_eq!(opts.retries, Some(1));
+
+        let rewrites: Vec<&str> = opts.rewrite_host.iter().map(|r| r.to.as_str()).collect();
+        assert_eq!(rewrites, vec!["cli.internal", "config.internal", "db.internal"]);
+    }
+
+    #[test]
+    fn splits_a_line_number_off_the_file() {
+        let opts = opts(&["requests.md:12"]);
+
+        assert_eq!(opts.filename(), Some("requests.md"));
+        assert_eq!(opts.at_line(), Some(12));
+    }
+
+    #[test]
+    fn keeps_windows_paths_whole() {
+        let with_line = opts(&[r"C:\dir\file.md:12"]);
+        assert_eq!(with_line.filename(), Some(r"C:\dir\file.md"));
+        assert_eq!(with_line.at_line(), Some(12));
+
+        let without_line = opts(&[r"C:\dir\file.md"]);
+        assert_eq!(without_line.filename(), Some(r"C:\dir\file.md"));
+        assert_eq!(without_line.at_line(), None);
+    }
+
+    #[test]
+    fn line_flag_wins_over_the_file() {
+        let opts = opts(&["--line=3", "requests.md:12"]);
+
+        assert_eq!(opts.filename(), Some("requests.md"));
+        assert_eq!(opts.at_line(), Some(3));
+    }
+
+    #[test]
+    fn minify_sends_indented_json_on_one_line() {
+        let mut req = post("application/json", "{\n  \"name\": \"foo\",\n  \"series\": 8\n}");
+        JsonBody::Minify.apply(&mut req);
+
+        assert_eq!(req.body.as_deref(), Some(r#"{"name":"foo","series":8}"#));
+    }
+
+    #[test]
+    fn pretty_indents_json_by_two() {
+        let mut req = post("application/json", r#"{"name":"foo"}"#);
+        JsonBody::Pretty.apply(&mut req);
+
+        assert_eq!(req.body.as_deref(), Some("{\n  \"name\": \"foo\"\n}"));
+    }
+
+    #[test]
+    fn json_body_leaves_other_bodies_alone() {
+        let mut text = post("text/plain", "{ \"name\": \"foo\" }");
+        JsonBody::Minify.apply(&mut text);
+        assert_eq!(text.body.as_deref(), Some("{ \"name\": \"foo\" }\n"));
+
+        let mut broken = post("application/json", "{ \"name\": }");
+        JsonBody::Minify.apply(&mut broken);
+        assert_eq!(broken.body.as_deref(), Some("{ \"name\": }\n"));
+    }
+
+    #[test]
+    fn read_input_drops_a_leading_bom() {
+        let data = read_input(&b"\xef\xbb\xbf# Requests\n"[..]).unwrap();
+        assert_eq!(data, "# Requests\n");
+    }
+
+    #[test]
+    fn read_input_passes_plain_markdown_through() {
+        let data = read_input(&b"# Requests\n\nGET /widgets\n"[..]).unwrap();
+        assert_eq!(data, "# Requests\n\nGET /widgets\n");
+    }
+
+    #[test]
+    fn read_input_inflates_gzipped_markdown() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"# Requests\n").unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(read_input(&gzipped[..]).unwrap(), "# Requests\n");
+    }
+
+    #[test]
+    fn read_input_replaces_invalid_utf8() {
+        let data = read_input(&b"GET /caf\xe9\n"[..]).unwrap();
+        assert_eq!(data, "GET /caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn rewrites_only_the_matching_host() {
+        let rewrite: HostRewrite = "localhost=api.internal".parse().unwrap();
+
+        assert_eq!(
+            rewrite.rewrite("http://localhost:3000/widgets?id=1").unwrap().as_str(),
+            "http://api.internal:3000/widgets?id=1"
+        );
+        assert!(rewrite.rewrite("http://example.com/widgets").is_none());
+    }
+
+    #[test]
+    fn rewriting_the_host_keeps_its_base_path() {
+        let opts = Opts::try_parse_from(["req_md", "--rewrite-host", "localhost=api.internal"]).unwrap();
+        let mut req = request("```\nGET /widgets\nHost: http://localhost:3000/api\n```\n");
+        opts.apply_overrieds(&mut req);
+
+        assert_eq!(req.url(), "http://api.internal:3000/api/widgets");
+    }
+
+    #[test]
+    fn rewrites_the_port_when_given() {
+        let rewrite: HostRewrite = "localhost=api.internal:8080".parse().unwrap();
+
+        assert_eq!(
+            rewrite.rewrite("http://localhost:3000/widgets").unwrap().as_str(),
+            "http://api.internal:8080/widgets"
+        );
+    }
+
+    #[test]
+    fn rejects_rewrites_that_are_not_hosts() {
+        assert!("localhost".parse::<HostRewrite>().is_err());
+        assert!("localhost=".parse::<HostRewrite>().is_err());
+        assert!("localhost=api.internal:http".parse::<HostRewrite>().is_err());
+        assert!("localhost=api internal".parse::<HostRewrite>().is_err());
+    }
+}
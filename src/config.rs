@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use crate::application::{HostRewrite, OutputFormat, RedirectPolicy, TimeoutDuration};
+use crate::error::Error;
+
+pub const CONFIG_FILE: &str = "reqmd.toml";
+
+/// Defaults for the command line options, read from `reqmd.toml` in the
+/// current directory when there is one.  Keys match the flags:
+///
+/// ```toml
+/// output = "markdown"
+/// timeout = "15sec"
+/// connect_timeout = "500ms"
+/// redirects = "none"
+/// retries = 3
+/// retry_on = [429, 503]
+/// rewrite_host = ["localhost=api.internal"]
+/// https_only = true
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub output: Option<OutputFormat>,
+    pub timeout: Option<TimeoutDuration>,
+    pub connect_timeout: Option<TimeoutDuration>,
+    pub redirects: Option<RedirectPolicy>,
+    pub retries: Option<u32>,
+    pub retry_on: Vec<u16>,
+    pub rewrite_host: Vec<HostRewrite>,
+    pub https_only: bool,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Error> {
+        match fs::read_to_string(CONFIG_FILE) {
+            Ok(data) => toml::from_str(&data).map_err(Error::Config),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(Error::ReadConfig(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reads_every_documented_key() {
+        let config: Config = toml::from_str(r#"
+            output = "markdown"
+            timeout = "15sec"
+            connect_timeout = "500ms"
+            redirects = "none"
+            retries = 3
+            retry_on = [429, 503]
+            rewrite_host = ["localhost=api.internal"]
+            https_only = true
+        "#).unwrap();
+
+        assert!(matches!(config.output, Some(OutputFormat::MarkDown)));
+        assert_eq!(config.timeout.map(|t| t.duration), Some(Duration::from_secs(15)));
+        assert_eq!(config.connect_timeout.map(|t| t.duration), Some(Duration::from_millis(500)));
+        assert!(matches!(config.redirects, Some(RedirectPolicy::None)));
+        assert_eq!(config.retries, Some(3));
+        assert_eq!(config.retry_on, vec![429, 503]);
+        assert_eq!(config.rewrite_host[0].from, "localhost");
+        assert_eq!(config.rewrite_host[0].to, "api.internal");
+        assert!(config.https_only);
+    }
+
+    #[test]
+    fn every_key_is_optional() {
+        let config: Config = toml::from_str("").unwrap();
+
+        assert!(config.output.is_none() && config.timeout.is_none() && config.retries.is_none());
+        assert!(config.retry_on.is_empty() && config.rewrite_host.is_empty());
+        assert!(!config.https_only);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let err = toml::from_str::<Config>("time_out = \"15sec\"").err().unwrap();
+        assert!(err.to_string().contains("time_out"));
+    }
+
+    #[test]
+    fn bad_timeouts_are_rejected() {
+        assert!(toml::from_str::<Config>("timeout = \"soon\"").is_err());
+    }
+}
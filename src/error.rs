@@ -0,0 +1,193 @@
+use std::error;
+use std::fmt;
+use std::io;
+use crate::config::CONFIG_FILE;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the markdown input failed
+    Io(io::Error),
+    /// The reqmd.toml config file is there but couldn't be read
+    ReadConfig(io::Error),
+    /// The reqmd.toml config file couldn't be understood
+    Config(toml::de::Error),
+    /// No file was given and nothing was piped in
+    NoInput,
+    /// The input was read but there are no requests in it
+    NoRequests,
+    /// Setting up the file watcher failed
+    Watch(notify::Error),
+    /// Sending the request or reading its response failed
+    Send(reqwest::Error),
+    /// A plain http request, at this line, was refused by --https-only
+    Insecure(u32),
+    /// Checking the requests found this many problems
+    Check(usize),
+    /// Warming up found this many undefined variables or unreachable hosts
+    Warmup(usize),
+    /// Writing to the --record file failed
+    Record(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "unable to read input: {}", err),
+            Error::ReadConfig(err) => write!(f, "unable to read {}: {}", CONFIG_FILE, err),
+            Error::Config(err) => write!(f, "invalid {}: {}", CONFIG_FILE, err),
+            Error::NoInput => write!(f, "no input, give a file or pipe in markdown"),
+            Error::NoRequests => write!(f, "no requests found in the input"),
+            Error::Watch(err) => write!(f, "unable to watch file: {}", err),
+            Error::Send(err) => write!(f, "{}", err),
+            Error::Insecure(line) => write!(f, "line {}: refusing to send without https", line),
+            Error::Check(problems) => write!(f, "check found {} problem(s)", problems),
+            Error::Warmup(problems) => write!(f, "warmup found {} problem(s)", problems),
+            Error::Record(err) => write!(f, "unable to write record: {}", err),
+        }
+    }
+}
+
+impl Error {
+    /// Status to exit the process with, finding no requests gets its own
+    /// so scripts can tell an empty file from a failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoRequests => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::ReadConfig(err) => Some(err),
+            Error::Config(err) => Some(err),
+            Error::NoInput => None,
+            Error::NoRequests => None,
+            Error::Watch(err) => Some(err),
+            Error::Send(err) => Some(err),
+            Error::Insecure(_) => None,
+            Error::Check(_) => None,
+            Error::Warmup(_) => None,
+            Error::Record(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Self {
+        Error::Watch(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Send(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    fn config_error() -> toml::de::Error {
+        toml::from_str::<toml::Value>("timeout =").unwrap_err()
+    }
+
+    fn send_error() -> reqwest::Error {
+        reqwest::blocking::Client::new().get("not a url").send().unwrap_err()
+    }
+
+    #[test]
+    fn io_errors() {
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "missing.md"));
+
+        assert_eq!(err.to_string(), "unable to read input: missing.md");
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn config_errors() {
+        let err = Error::Config(config_error());
+
+        assert!(err.to_string().starts_with("invalid reqmd.toml: "));
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn config_read_errors() {
+        let err = Error::ReadConfig(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"));
+
+        assert_eq!(err.to_string(), "unable to read reqmd.toml: permission denied");
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn record_errors() {
+        let err = Error::Record(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"));
+
+        assert_eq!(err.to_string(), "unable to write record: permission denied");
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(Error::NoInput.to_string(), "no input, give a file or pipe in markdown");
+        assert!(Error::NoInput.source().is_none());
+        assert_eq!(Error::NoInput.exit_code(), 1);
+
+        assert_eq!(Error::NoRequests.to_string(), "no requests found in the input");
+        assert!(Error::NoRequests.source().is_none());
+        assert_eq!(Error::NoRequests.exit_code(), 2);
+    }
+
+    #[test]
+    fn watch_errors() {
+        let err = Error::from(notify::Error::generic("no such file"));
+
+        assert!(err.to_string().starts_with("unable to watch file: "));
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn send_errors() {
+        let cause = send_error().to_string();
+        let err = Error::from(send_error());
+
+        assert_eq!(err.to_string(), cause);
+        assert!(err.source().is_some());
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn validation_errors() {
+        let insecure = Error::Insecure(12);
+        assert_eq!(insecure.to_string(), "line 12: refusing to send without https");
+        assert!(insecure.source().is_none());
+        assert_eq!(insecure.exit_code(), 1);
+
+        let check = Error::Check(3);
+        assert_eq!(check.to_string(), "check found 3 problem(s)");
+        assert!(check.source().is_none());
+        assert_eq!(check.exit_code(), 1);
+
+        let warmup = Error::Warmup(2);
+        assert_eq!(warmup.to_string(), "warmup found 2 problem(s)");
+        assert!(warmup.source().is_none());
+        assert_eq!(warmup.exit_code(), 1);
+    }
+}
@@ -0,0 +1,22 @@
+//! Requests built from small markdown snippets, shared by the tests
+
+use crate::parser::parse_requests;
+use crate::req::Request;
+
+/// The last request found in `markdown`
+pub fn request(markdown: &str) -> Request {
+    parse_requests(markdown).pop().expect("no request in the markdown")
+}
+
+/// `GET /widgets` sent to `host`
+pub fn get(host: &str) -> Request {
+    request(&format!("```\nGET /widgets\nHost: {}\n```\n", host))
+}
+
+/// `POST /widgets` with a `Content-Type` and a body that starts on line 7
+pub fn post(content_type: &str, body: &str) -> Request {
+    request(&format!(
+        "```\nPOST /widgets\nContent-Type: {}\nHost: http://localhost\n```\n```\n{}\n```\n",
+        content_type, body
+    ))
+}
@@ -1,81 +1,490 @@
 mod application;
+mod config;
+mod error;
+#[cfg(test)]
+mod fixtures;
 mod parser;
 mod req;
 mod variables;
 mod pretty_output;
+mod record;
+mod watch;
+#[cfg(feature = "templating")]
+mod templates;
 
+use application::ListFormat;
 use application::OutputFormat::{Raw, MarkDown};
 use pretty_output::PrettyOutput;
 use dotenv::dotenv;
+use error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::collections::BTreeSet;
+use std::env;
+use std::net::SocketAddr;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+const DNS_TIMEOUT: Duration = Duration::from_secs(3);
 
 fn main() {
     dotenv().ok();
 
-    let opts = application::get_opts();
+    if let Err(err) = application::get_opts().and_then(|opts| run(&opts)) {
+        eprintln!("{}", err);
+        process::exit(err.exit_code());
+    }
+}
 
-    if opts.list_requests {
-        list_requests(&opts);
+fn run(opts: &application::Opts) -> Result<(), Error> {
+    if opts.list_vars {
+        list_vars(opts)
+    } else if opts.check {
+        check_requests(opts)
+    } else if opts.warmup {
+        warmup(opts)
+    } else if opts.list_requests && opts.watch {
+        watch_requests(opts)
+    } else if opts.list_requests {
+        list_requests(opts)
     } else {
-        run_request(&opts);
+        run_request(opts)
     }
 }
 
-fn list_requests(opts: &application::Opts) {
-    let data = opts.input().unwrap();
-    let vars = variables::Variables::new(&data);
-    let mut reqs = parser::parse_requests(&vars.expand(&data));
+fn requests(opts: &application::Opts) -> Result<Vec<req::Request>, Error> {
+    let data = opts.input()?;
+    let reqs = parse(opts, &data);
+
+    if reqs.is_empty() {
+        return Err(Error::NoRequests);
+    }
+
+    Ok(reqs)
+}
+
+fn parse(opts: &application::Opts, data: &str) -> Vec<req::Request> {
+    let vars = variables::Variables::new(data);
+    let mut reqs = parser::parse_requests(&vars.expand(data));
+
+    for req in &mut reqs {
+        #[cfg(feature = "templating")]
+        templates::expand_body(req);
+
+        opts.apply_overrieds(req);
+    }
+
+    reqs
+}
+
+fn list_requests(opts: &application::Opts) -> Result<(), Error> {
+    print_requests(opts, &requests(opts)?);
+    Ok(())
+}
+
+fn watch_requests(opts: &application::Opts) -> Result<(), Error> {
+    let filename = opts.filename().ok_or(Error::NoInput)?;
 
-    match opts.at_line() {
-        None => {
-            for req in &mut reqs {
-                opts.apply_overrieds(req);
+    let clear = atty::is(atty::Stream::Stdout);
+
+    watch::watch_file(Path::new(filename), || {
+        if clear {
+            print!("\x1B[2J\x1B[1;1H");
+        }
+
+        match File::open(filename).and_then(application::read_input) {
+            Ok(data) => print_requests(opts, &parse(opts, &data)),
+            Err(err) => eprintln!("{}", err),
+        }
+
+        io::stdout().flush().ok();
+    })?;
+
+    Ok(())
+}
+
+fn print_requests(opts: &application::Opts, reqs: &[req::Request]) {
+    let reqs: Vec<&req::Request> = match opts.at_line() {
+        None => reqs.iter().collect(),
+        Some(line_number) => reqs
+            .iter()
+            .filter(|req| req.meta.line_range.contains(&line_number))
+            .take(1)
+            .collect(),
+    };
+
+    match opts.list_format() {
+        ListFormat::Debug => {
+            for req in reqs {
                 println!("{:#?}", req);
             }
         },
-        Some(line_number) => {
-            for req in &mut reqs {
-                if req.meta.line_range.contains(&line_number) {
-                    opts.apply_overrieds(req);
-                    println!("{:#?}", req);
-                    return;
-                }
+        ListFormat::Table => print_table(&reqs),
+    }
+}
+
+fn print_table(reqs: &[&req::Request]) {
+    let color = atty::is(atty::Stream::Stdout);
+
+    let columns = if color { terminal_width() } else { None }
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok());
+
+    for row in table(reqs, color, columns) {
+        println!("{}", row);
+    }
+}
+
+/// One aligned row per request of its line, method, and url, under a
+/// header row.  Urls are cut down to fit when the width in `columns` is
+/// known, and methods are only colored when `color` is set.
+fn table(reqs: &[&req::Request], color: bool, columns: Option<usize>) -> Vec<String> {
+    let line_width = reqs
+        .iter()
+        .map(|req| req.meta.line_range.start.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("LINE".len());
+
+    let method_width = reqs
+        .iter()
+        .map(|req| req.method.len())
+        .max()
+        .unwrap_or(0)
+        .max("METHOD".len());
+
+    let url_width =
+        columns.map(|columns| columns.saturating_sub(line_width + method_width + 4).max(10));
+
+    let mut rows = vec![format!(
+        "{:>lw$}  {:mw$}  URL",
+        "LINE", "METHOD", lw = line_width, mw = method_width
+    )];
+
+    for req in reqs {
+        let method = format!("{:mw$}", req.method, mw = method_width);
+        let method = match method_color(&req.method) {
+            Some(code) if color => format!("\x1B[{}m{}\x1B[0m", code, method),
+            _ => method,
+        };
+
+        rows.push(format!(
+            "{:>lw$}  {}  {}",
+            req.meta.line_range.start,
+            method,
+            truncate(&req.redacted_url(), url_width),
+            lw = line_width
+        ));
+    }
+
+    rows
+}
+
+/// Columns of the terminal stdout is writing to, when it is one
+fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| width as usize)
+}
+
+/// The ANSI color code a method is shown in
+fn method_color(method: &str) -> Option<u8> {
+    match method {
+        "GET" => Some(32),
+        "POST" => Some(33),
+        "PUT" => Some(34),
+        "PATCH" => Some(36),
+        "DELETE" => Some(31),
+        _ => None,
+    }
+}
+
+/// Cuts `text` down to `width` characters, marking that it was cut
+fn truncate(text: &str, width: Option<usize>) -> String {
+    match width {
+        Some(width) if text.chars().count() > width => {
+            let mut cut: String = text.chars().take(width - 1).collect();
+            cut.push('…');
+            cut
+        },
+        _ => text.to_string(),
+    }
+}
+
+fn list_vars(opts: &application::Opts) -> Result<(), Error> {
+    let data = opts.input()?;
+    let vars = variables::Variables::new(&data);
+
+    for (var, lines) in variables::references(&data) {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+
+        println!(
+            "{} ({}) {} {}",
+            var,
+            vars.source_of(&var).unwrap_or("undefined"),
+            if lines.len() == 1 { "line" } else { "lines" },
+            lines.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+fn check_requests(opts: &application::Opts) -> Result<(), Error> {
+    let data = opts.input()?;
+    let vars = variables::Variables::new(&data);
+
+    let reqs = parse(opts, &data);
+
+    let mut errors: Vec<String> = parser::invalid_request_lines(&vars.expand(&data))
+        .into_iter()
+        .map(|line| format!("line {}: looks like a request but has no uri or host", line))
+        .collect();
+
+    for (var, lines) in vars.undefined(&data) {
+        errors.extend(lines.iter().map(|line| format!("line {}: undefined: {}", line, var)));
+    }
+
+    errors.extend(
+        reqs.iter()
+            .filter_map(|req| refuse_insecure(opts, req).err())
+            .map(|err| err.to_string())
+    );
+
+    let warnings: Vec<String> = reqs
+        .iter()
+        .flat_map(|req| req.warnings())
+        .collect();
+
+    for error in &errors {
+        println!("error: {}", error);
+    }
+
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+
+    let problems =
+        if opts.strict {
+            errors.len() + warnings.len()
+        } else {
+            errors.len()
+        };
+
+    if problems > 0 {
+        return Err(Error::Check(problems));
+    }
+
+    Ok(())
+}
+
+fn warmup(opts: &application::Opts) -> Result<(), Error> {
+    let data = opts.input()?;
+    let vars = variables::Variables::new(&data);
+    let mut problems = 0;
+
+    for var in vars.undefined(&data).keys() {
+        println!("undefined: {}", var);
+        problems += 1;
+    }
+
+    let hosts: BTreeSet<String> = parse(opts, &data)
+        .into_iter()
+        .map(|req| req.host)
+        .collect();
+
+    for host in &hosts {
+        match resolve(host) {
+            Ok(addr) => println!("ok: {} ({})", req::redact(host), addr),
+            Err(err) => {
+                println!("unreachable: {} {}", req::redact(host), err);
+                problems += 1;
             }
         }
     }
+
+    if problems > 0 {
+        return Err(Error::Warmup(problems));
+    }
+
+    Ok(())
 }
 
-fn run_request(opts: &application::Opts) {
-    let data = opts.input().unwrap();
-    let vars = variables::Variables::new(&data);
-    let mut reqs = parser::parse_requests(&vars.expand(&data));
+/// DNS lookup for a host without connecting to it.  The lookup can't be
+/// cancelled so it's given its own thread and abandoned if it's too slow.
+fn resolve(host: &str) -> Result<SocketAddr, String> {
+    let url = Url::parse(host).map_err(|err| err.to_string())?;
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        tx.send(url.socket_addrs(|| None)).ok();
+    });
+
+    match rx.recv_timeout(DNS_TIMEOUT) {
+        Ok(Ok(addrs)) => addrs.into_iter().next().ok_or_else(|| "no addresses found".to_string()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    }
+}
+
+fn run_request(opts: &application::Opts) -> Result<(), Error> {
+    let reqs = requests(opts)?;
     let line = opts.at_line().unwrap_or(1);
 
 
-    for req in &mut reqs {
+    for req in &reqs {
         if req.meta.line_range.contains(&line) {
-            opts.apply_overrieds(req);
-            match req.send() {
-                Ok(resp) => {
-                    match opts.output {
-                        Raw => println!("{}", resp.text().unwrap()),
-                        MarkDown => println!("{}", PrettyOutput::pretty_output(resp)),
-                    }
-                },
-                Err(err) => eprintln!("{}", err),
-            }
-            return;
+            return send_request(opts, req);
         }
     }
 
     if let Some(req) = reqs.iter().nth(0) {
-        match req.send() {
-            Ok(resp) => {
-                match opts.output {
-                    Raw => println!("{}", resp.text().unwrap()),
-                    MarkDown => println!("{}", PrettyOutput::pretty_output(resp)),
-                }
-            },
-            Err(err) => eprintln!("{}", err),
+        send_request(opts, req)?;
+    }
+
+    Ok(())
+}
+
+/// Stops a plain http request from going out when `--https-only` is set
+fn refuse_insecure(opts: &application::Opts, req: &req::Request) -> Result<(), Error> {
+    if opts.https_only && !req.is_https() {
+        return Err(Error::Insecure(req.meta.line_range.start));
+    }
+
+    Ok(())
+}
+
+fn send_request(opts: &application::Opts, req: &req::Request) -> Result<(), Error> {
+    refuse_insecure(opts, req)?;
+
+    for warning in req.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+
+    let mut resp = req.send()?;
+
+    if let Some(path) = &opts.record {
+        resp = record::append(path, req, resp)?;
+    }
+
+    match opts.output() {
+        Raw => println!("{}", resp.text()?),
+        MarkDown => println!("{}", PrettyOutput::pretty_output(resp)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+
+    fn opts_for(markdown: &str, name: &str) -> (application::Opts, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!("req_md-{}-{}.md", name, process::id()));
+        fs::write(&path, markdown).unwrap();
+
+        let opts = application::Opts::try_parse_from(["req_md", path.to_str().unwrap()]).unwrap();
+        (opts, path)
+    }
+
+    #[test]
+    fn empty_documents_have_no_requests() {
+        for (name, markdown) in &[("empty", ""), ("prose", "# Notes\n\nnothing to send\n")] {
+            let (opts, path) = opts_for(markdown, name);
+            let result = requests(&opts);
+            fs::remove_file(path).unwrap();
+
+            match result {
+                Err(err @ Error::NoRequests) => assert_eq!(err.exit_code(), 2),
+                _ => panic!("expected no requests for {:?}", markdown),
+            }
+        }
+    }
+
+    #[test]
+    fn https_only_refuses_plain_http() {
+        let opts = application::Opts::try_parse_from(["req_md", "--https-only"]).unwrap();
+        let markdown = "# API\n\n```\nGET /widgets\nHost: http://localhost:3000\n```\n\n```\nGET /widgets\nHost: https://api.example.com\n```\n";
+        let reqs = parse(&opts, markdown);
+
+        match refuse_insecure(&opts, &reqs[0]) {
+            Err(Error::Insecure(line)) => assert_eq!(line, 3),
+            _ => panic!("expected plain http to be refused"),
+        }
+        assert!(refuse_insecure(&opts, &reqs[1]).is_ok());
+    }
+
+    #[test]
+    fn plain_http_is_allowed_without_https_only() {
+        let opts = application::Opts::try_parse_from(["req_md"]).unwrap();
+        let reqs = parse(&opts, "```\nGET /widgets\nHost: http://localhost:3000\n```\n");
+
+        assert!(refuse_insecure(&opts, &reqs[0]).is_ok());
+    }
+
+    #[test]
+    fn warmup_counts_undefined_variables() {
+        let markdown = "```\nGET /me\nAuthorization: Bearer $WARMUP_UNSET_TOKEN\nHost: http://127.0.0.1:1\n```\n";
+        let (opts, path) = opts_for(markdown, "warmup-undefined");
+        let result = warmup(&opts);
+        fs::remove_file(path).unwrap();
+
+        match result {
+            Err(Error::Warmup(problems)) => assert_eq!(problems, 1),
+            _ => panic!("expected the undefined variable to be a problem"),
+        }
+    }
+
+    #[test]
+    fn warmup_passes_when_everything_resolves() {
+        let markdown = "TOKEN: abc\n\n```\nGET /me\nAuthorization: Bearer $TOKEN\nHost: http://127.0.0.1:1\n```\n";
+        let (opts, path) = opts_for(markdown, "warmup-ok");
+        let result = warmup(&opts);
+        fs::remove_file(path).unwrap();
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn truncate_cuts_only_past_the_width() {
+        assert_eq!(truncate("http://localhost", Some(16)), "http://localhost");
+        assert_eq!(truncate("http://localhost", Some(15)), "http://localho…");
+        assert_eq!(truncate("http://localhost", None), "http://localhost");
+    }
+
+    #[test]
+    fn empty_tables_are_only_the_header() {
+        assert_eq!(table(&[], false, Some(80)), vec!["LINE  METHOD  URL"]);
+    }
+
+    #[test]
+    fn tables_are_aligned_and_uncolored_off_a_tty() {
+        let opts = application::Opts::try_parse_from(["req_md"]).unwrap();
+        let reqs = parse(&opts, "```\nGET /widgets\nHost: http://localhost:4000\n```\n\n```\nDELETE /widgets/1\nHost: http://localhost:4000\n```\n");
+        let reqs: Vec<&req::Request> = reqs.iter().collect();
+
+        assert_eq!(
+            table(&reqs, false, None),
+            vec![
+                "LINE  METHOD  URL",
+                "   1  GET     http://localhost:4000/widgets",
+                "   6  DELETE  http://localhost:4000/widgets/1",
+            ]
+        );
+        assert!(table(&reqs, true, None)[1].contains("\x1B[32mGET   \x1B[0m"));
+        assert_eq!(table(&reqs, false, Some(30))[2], "   6  DELETE  http://localhos…");
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let opts = application::Opts::try_parse_from(["req_md", "/no/such/file.md"]).unwrap();
+
+        match requests(&opts) {
+            Err(err @ Error::Io(_)) => assert_eq!(err.exit_code(), 1),
+            _ => panic!("expected an io error"),
         }
     }
 }
@@ -2,13 +2,16 @@ use crate::req::{Request, Meta};
 use comrak::arena_tree::Node;
 use comrak::nodes::{Ast, NodeValue::*};
 use comrak::{parse_document, Arena, ComrakOptions};
+use regex::Regex;
 use std::cell::RefCell;
 use std::ops::Range;
 use url::{Position, Url};
 
 type MarkDown<'a> = Node<'a, RefCell<Ast>>;
 
-const VALID_METHODS: &'static [&'static str] = &["GET", "HEAD", "POST", "PUT", "DELETE", "PATCH"];
+const VALID_METHODS: &'static [&'static str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS", "TRACE", "CONNECT",
+];
 
 pub fn parse_requests(input: &str) -> Vec<Request> {
     let arena = Arena::new();
@@ -21,39 +24,70 @@ pub fn parse_requests(input: &str) -> Vec<Request> {
     .collect()
 }
 
+/// Lines of the code blocks that start like a request but can't be made
+/// into one, usually because they are missing a `Host:` header.  Only
+/// standard methods count, a block like `WORKDIR /app` is some other
+/// kind of code rather than a broken request.
+pub fn invalid_request_lines(input: &str) -> Vec<u32> {
+    let arena = Arena::new();
+
+    parse_document(&arena, input, &ComrakOptions::default())
+    .children()
+    .filter(|node| node.is_req_block() && node.to_request().is_none())
+    .filter(|node| node.request_method().is_some_and(|method| VALID_METHODS.contains(&method.as_str())))
+    .map(|node| node.data.borrow().start_line)
+    .collect()
+}
+
+/// Parses the method and uri out of a request line.  The input may span
+/// several lines when the query string is continued with lines starting
+/// with `?` or `&`, anything after the request line is ignored.
+pub fn parse_request_line(input: &str) -> Option<(String, String)> {
+    let req_line = join_request_line(input);
+
+    let method = find_method(&req_line)?;
+    let uri = req_line.split_whitespace().nth(1)?;
+
+    Some((method, uri.to_string()))
+}
+
 trait ReqBlock {
     fn to_request(&self) -> Option<Request> {
         let meta = Meta {
             line_range: self.line_range().unwrap_or(0..0),
+            body_line: self.body_line(),
             // TODO: Come up with a way to set timeout in
             // the markdown dock
-            timeout: None
+            timeout: None,
+            connect_timeout: None,
+            redirects: None,
+            retries: 0,
+            retry_on: vec![],
         };
 
+        let mut headers = self.headers();
+        if let Some(content_type) = self.body_content_type() {
+            if !headers.iter().any(|header| header.to_lowercase().starts_with("content-type:")) {
+                headers.push(format!("Content-Type: {}", content_type));
+            }
+        }
+
         Some(Request {
             method: self.request_method()?,
             uri: self.request_uri()?,
             host: self.host()?,
-            headers: self.headers(),
+            headers,
             body: self.request_body(),
             meta,
         })
     }
 
     fn request_method(&self) -> Option<String> {
-        let req_line = self.request_line()?;
-
-        VALID_METHODS
-            .iter()
-            .find(|method| req_line.starts_with(*method))
-            .map(|method| method.to_string())
+        parse_request_line(&self.request_line()?).map(|(method, _)| method)
     }
 
     fn request_uri(&self) -> Option<String> {
-        self.request_line()?
-            .split_whitespace()
-            .nth(1)
-            .map(|uri| uri.to_string())
+        parse_request_line(&self.request_line()?).map(|(_, uri)| uri)
     }
 
     fn host(&self) -> Option<String> {
@@ -65,13 +99,15 @@ trait ReqBlock {
         self.headers()
             .iter()
             .find(|header| header.to_lowercase().starts_with("host: "))
-            .map(|header| header[6..].to_string())
+            .map(|header| with_scheme(header[6..].trim()))
     }
 
     fn is_req_block(&self) -> bool;
     fn request_line(&self) -> Option<String>;
     fn headers(&self) -> Vec<String>;
     fn request_body(&self) -> Option<String>;
+    fn body_content_type(&self) -> Option<String>;
+    fn body_line(&self) -> Option<u32>;
     fn line_range(&self) -> Option<Range<u32>>;
 }
 
@@ -87,9 +123,7 @@ impl<'a> ReqBlock for &'a MarkDown<'a> {
     fn is_req_block(&self) -> bool {
         if let CodeBlock(code) = &self.data.borrow().value {
             let string = String::from_utf8_lossy(&code.literal);
-            return VALID_METHODS
-                .iter()
-                .any(|method| string.starts_with(method));
+            return find_method(&string).is_some();
         }
 
         false
@@ -98,17 +132,7 @@ impl<'a> ReqBlock for &'a MarkDown<'a> {
     fn request_line(&self) -> Option<String> {
         if let CodeBlock(code) = &self.data.borrow().value {
             let block = String::from_utf8_lossy(&code.literal);
-            let lines = lines_for_req_line(&block);
-            let mut req_line = String::new();
-
-            block
-                .lines()
-                .take(lines)
-                .for_each(|line| {
-                    req_line += line.trim();
-                });
-
-            return Some(req_line);
+            return Some(join_request_line(&block));
         }
 
         None
@@ -137,6 +161,28 @@ impl<'a> ReqBlock for &'a MarkDown<'a> {
         }
     }
 
+    // ex: a body fenced as `json {content-type=application/vnd.api+json}`
+    fn body_content_type(&self) -> Option<String> {
+        match &self.next_sibling()?.data.borrow().value {
+            CodeBlock(code) => {
+                let info = String::from_utf8_lossy(&code.info);
+                let matcher = Regex::new(r"(?i)content-type=([^}\s]+)").unwrap();
+                matcher.captures(&info).map(|cap| cap[1].to_string())
+            },
+            _ => None,
+        }
+    }
+
+    fn body_line(&self) -> Option<u32> {
+        let node = self.next_sibling()?;
+
+        if node.is_a_code_block() {
+            Some(node.data.borrow().start_line + 1)
+        } else {
+            None
+        }
+    }
+
     fn line_range(&self) -> Option<Range<u32>> {
         let range = self.source_range()?;
 
@@ -153,6 +199,51 @@ impl<'a> ReqBlock for &'a MarkDown<'a> {
     }
 }
 
+/// The method a request line starts with, in any case, normalized to
+/// upper case.  Methods that aren't standard, like `PURGE`, are taken as
+/// written as long as they are upper case.  Either way it has to be
+/// followed by a uri, so a shell block starting `head -n 5` isn't a HEAD.
+fn find_method(line: &str) -> Option<String> {
+    let mut tokens = line.split_whitespace();
+    let token = tokens.next()?;
+
+    if !looks_like_uri(tokens.next()?) {
+        return None;
+    }
+
+    if let Some(method) = VALID_METHODS.iter().find(|method| method.eq_ignore_ascii_case(token)) {
+        return Some(method.to_string());
+    }
+
+    if token.bytes().all(|b| b.is_ascii_uppercase()) {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
+
+fn looks_like_uri(token: &str) -> bool {
+    token.starts_with('/') || token.starts_with('$') || token.contains("://")
+}
+
+/// A host without a scheme, such as `example.com:8080` or the scheme
+/// relative `//example.com`, defaults to https.
+fn with_scheme(host: &str) -> String {
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("https://{}", host.trim_start_matches('/'))
+    }
+}
+
+fn join_request_line(body: &str) -> String {
+    body
+        .lines()
+        .take(lines_for_req_line(body))
+        .map(|line| line.trim())
+        .collect()
+}
+
 fn lines_for_req_line(body: &str) -> usize {
     body
         .lines()
@@ -187,3 +278,110 @@ impl<'a> NodeInterrogation for &'a MarkDown<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::request;
+
+    #[test]
+    fn query_keeps_source_order_and_duplicates() {
+        let input = "```\nGET /x\n  ?b=1\n  &a=2\n  &b=3\nHost: http://localhost\n```\n";
+        let reqs = parse_requests(input);
+
+        assert_eq!(reqs.len(), 1);
+        assert!(reqs[0].url().ends_with("/x?b=1&a=2&b=3"));
+    }
+
+    #[test]
+    fn hosts_without_a_scheme_default_to_https() {
+        assert_eq!(with_scheme("example.com"), "https://example.com");
+        assert_eq!(with_scheme("example.com:8080"), "https://example.com:8080");
+        assert_eq!(with_scheme("//example.com"), "https://example.com");
+        assert_eq!(with_scheme("http://example.com"), "http://example.com");
+    }
+
+    #[test]
+    fn parses_a_single_request_line() {
+        assert_eq!(
+            parse_request_line("GET /widgets?id=1"),
+            Some(("GET".to_string(), "/widgets?id=1".to_string()))
+        );
+    }
+
+    #[test]
+    fn joins_query_continuation_lines() {
+        assert_eq!(
+            parse_request_line("GET /filter\n      ?zip=90210\n      &radius=50"),
+            Some(("GET".to_string(), "/filter?zip=90210&radius=50".to_string()))
+        );
+    }
+
+    #[test]
+    fn ignores_lines_after_the_request_line() {
+        assert_eq!(
+            parse_request_line("POST /widgets\n  ?dry=true\nContent-Type: application/json\nHost: localhost"),
+            Some(("POST".to_string(), "/widgets?dry=true".to_string()))
+        );
+    }
+
+    #[test]
+    fn vendor_json_content_type_comes_from_the_body_fence() {
+        let input = "```\nPOST /widgets\nHost: http://localhost\n```\n```json {content-type=application/vnd.api+json}\n{\"data\": {}}\n```\n";
+        let req = request(input);
+
+        assert_eq!(req.content_type(), Some("application/vnd.api+json"));
+        assert!(req.is_json());
+        assert!(req.warnings().is_empty());
+    }
+
+    #[test]
+    fn content_type_header_wins_over_the_body_fence() {
+        let input = "```\nPOST /widgets\nContent-Type: application/json\nHost: http://localhost\n```\n```json {content-type=application/vnd.api+json}\n{}\n```\n";
+        let req = request(input);
+
+        assert_eq!(req.content_type(), Some("application/json"));
+        assert_eq!(req.headers.iter().filter(|h| h.to_lowercase().starts_with("content-type:")).count(), 1);
+    }
+
+    #[test]
+    fn methods_match_in_any_case() {
+        for (written, method) in &[("get", "GET"), ("Post", "POST"), ("DELETE", "DELETE")] {
+            let line = format!("{} /x", written);
+            assert_eq!(find_method(&line).as_deref(), Some(*method));
+        }
+    }
+
+    #[test]
+    fn custom_methods_are_sent_as_written() {
+        let reqs = parse_requests("```\nPURGE /cache\nHost: http://localhost\n```\n");
+
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0].method, "PURGE");
+        assert_eq!(find_method("purge /cache"), None);
+        assert_eq!(find_method("options /x").as_deref(), Some("OPTIONS"));
+    }
+
+    #[test]
+    fn only_standard_methods_are_invalid_requests() {
+        let input = "```dockerfile\nWORKDIR /app\nCOPY /src /dst\n```\n\ntext\n\n```\nGET /x\n```\n";
+
+        assert_eq!(parse_requests(input).len(), 0);
+        assert_eq!(invalid_request_lines(input), vec![8]);
+    }
+
+    #[test]
+    fn methods_need_a_uri_after_them() {
+        assert_eq!(find_method("head -n 5 file"), None);
+        assert_eq!(find_method("get some things"), None);
+        assert_eq!(find_method("GET $API/users").as_deref(), Some("GET"));
+        assert_eq!(find_method("GET https://example.com/x").as_deref(), Some("GET"));
+    }
+
+    #[test]
+    fn non_request_lines_are_none() {
+        assert_eq!(parse_request_line("fn main() {}"), None);
+        assert_eq!(parse_request_line("GET"), None);
+        assert_eq!(parse_request_line(""), None);
+    }
+}
@@ -1,8 +1,13 @@
 use std::ops::Range;
-use crate::application::TimeoutDuration;
+use crate::application::{RedirectPolicy, TimeoutDuration};
 
 #[derive(Debug)]
 pub struct Meta {
     pub line_range: Range<u32>,
+    pub body_line: Option<u32>,
     pub timeout: Option<TimeoutDuration>,
+    pub connect_timeout: Option<TimeoutDuration>,
+    pub redirects: Option<RedirectPolicy>,
+    pub retries: u32,
+    pub retry_on: Vec<u16>,
 }
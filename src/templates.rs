@@ -0,0 +1,85 @@
+use chrono::{SecondsFormat, Utc};
+use rand::Rng;
+use regex::{Captures, Regex};
+use uuid::Uuid;
+use crate::req::Request;
+
+/// Expands the built-in `{{...}}` template functions found in the body
+/// of a request.  This runs after variable expansion, and anything it
+/// does not recognize is left in the body exactly as written.
+///
+/// - `{{uuid}}` a random v4 uuid, different for every placeholder
+/// - `{{now}}` the current time as ISO-8601 UTC, ex: `2023-02-14T17:03:09Z`
+/// - `{{randomInt MIN MAX}}` a random integer from `MIN` to `MAX` inclusive
+pub fn expand_body(request: &mut Request) {
+    if let Some(body) = &request.body {
+        request.body = Some(expand(body));
+    }
+}
+
+fn expand(input: &str) -> String {
+    let matcher = Regex::new(r"\{\{\s*(\w+)((?:\s+[^\s}]+)*)\s*\}\}").unwrap();
+
+    matcher
+        .replace_all(input, |cap: &Captures| {
+            let args: Vec<&str> = cap[2].split_whitespace().collect();
+            call(&cap[1], &args).unwrap_or_else(|| cap[0].to_string())
+        })
+        .to_string()
+}
+
+fn call(name: &str, args: &[&str]) -> Option<String> {
+    match (name, args) {
+        ("uuid", []) => Some(Uuid::new_v4().to_string()),
+        ("now", []) => Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("randomInt", [min, max]) => {
+            let min: i64 = min.parse().ok()?;
+            let max: i64 = max.parse().ok()?;
+
+            if min > max {
+                return None;
+            }
+
+            Some(rand::thread_rng().gen_range(min..=max).to_string())
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_functions_are_left_as_written() {
+        assert_eq!(expand(r#"{"id": "{{foo}}"}"#), r#"{"id": "{{foo}}"}"#);
+    }
+
+    #[test]
+    fn random_int_is_within_its_bounds() {
+        assert_eq!(expand("{{randomInt 5 5}}"), "5");
+
+        let value: i64 = expand("{{randomInt -3 3}}").parse().unwrap();
+        assert!((-3..=3).contains(&value));
+    }
+
+    #[test]
+    fn random_int_with_min_over_max_is_left_as_written() {
+        assert_eq!(expand("{{randomInt 9 1}}"), "{{randomInt 9 1}}");
+    }
+
+    #[test]
+    fn now_is_iso_8601_utc() {
+        let matcher = Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z$").unwrap();
+        assert!(matcher.is_match(&expand("{{now}}")));
+    }
+
+    #[test]
+    fn uuid_is_a_new_v4_uuid() {
+        let first = Uuid::parse_str(&expand("{{uuid}}")).unwrap();
+        let second = Uuid::parse_str(&expand("{{ uuid }}")).unwrap();
+
+        assert_eq!(first.get_version_num(), 4);
+        assert_ne!(first, second);
+    }
+}
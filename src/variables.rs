@@ -1,6 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::ops::Range;
 use regex::Regex;
+use crate::parser::parse_requests;
+
+/// What a variable can be named, both where it's defined as `NAME: value`
+/// and where it's used as `$NAME` or `${NAME}`
+const NAME: &str = r"[A-Za-z_][A-Za-z0-9_-]*";
 
 #[derive(Debug)]
 pub struct Variables {
@@ -20,7 +26,7 @@ impl Variables {
             );
         }
 
-        let matcher = Regex::new(r"^([^:\s]+):(.+)$").unwrap();
+        let matcher = Regex::new(&format!(r"^({}):(.+)$", NAME)).unwrap();
         for line in input.lines() {
             if let Some(cap) = matcher.captures(line) {
                 vars.insert(
@@ -39,14 +45,114 @@ impl Variables {
     pub fn expand(&self, input: &str) -> String {
         let mut string = input.to_owned();
 
-        for (var, val) in &self.vars {
-            string = string.replace(var, val);
-        }
+        // longest first so `$API` doesn't eat the start of `$API-HOST`,
+        // and the file's own variables before the environment's
+        let mut pairs: Vec<(&String, &String)> = self.vars
+            .iter()
+            .chain(self.envs.iter().filter(|(var, _)| !self.vars.contains_key(*var)))
+            .collect();
+        pairs.sort_by_key(|(var, _)| std::cmp::Reverse(var.len()));
 
-        for (var, val) in &self.envs {
-            string = string.replace(var, val);
+        for (var, val) in pairs {
+            string = string
+                .replace(&braced(var), val)
+                .replace(var, val);
         }
 
         string
     }
+
+    /// Where a referenced `$NAME` gets its value from, if anywhere
+    pub fn source_of(&self, var: &str) -> Option<&'static str> {
+        if self.vars.contains_key(var) {
+            Some("file")
+        } else if self.envs.contains_key(var) {
+            Some("env")
+        } else {
+            None
+        }
+    }
+
+    /// The variables referenced in the input that have no value, with the
+    /// line numbers they show up on.  Request bodies are left out since
+    /// `$` is ordinary text in them, such as `$schema` and `$ref` in JSON.
+    pub fn undefined(&self, input: &str) -> BTreeMap<String, Vec<usize>> {
+        let bodies: Vec<Range<u32>> = parse_requests(&self.expand(input))
+            .iter()
+            .filter_map(|req| Some(req.meta.body_line?..req.meta.line_range.end))
+            .collect();
+
+        references(input)
+            .into_iter()
+            .filter(|(var, _)| self.source_of(var).is_none())
+            .filter_map(|(var, lines)| {
+                let lines: Vec<usize> = lines
+                    .into_iter()
+                    .filter(|line| !bodies.iter().any(|body| body.contains(&(*line as u32))))
+                    .collect();
+
+                if lines.is_empty() { None } else { Some((var, lines)) }
+            })
+            .collect()
+    }
+}
+
+/// Every `$NAME` or `${NAME}` referenced in the input, as `$NAME`, with
+/// the line numbers it shows up on.
+pub fn references(input: &str) -> BTreeMap<String, Vec<usize>> {
+    let matcher = Regex::new(&format!(r"\$\{{({0})\}}|\$({0})", NAME)).unwrap();
+    let mut refs: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+    for (index, line) in input.lines().enumerate() {
+        for cap in matcher.captures_iter(line) {
+            let name = cap.get(1).or_else(|| cap.get(2)).unwrap().as_str();
+            let lines = refs.entry(["$", name].concat()).or_default();
+
+            if lines.last() != Some(&(index + 1)) {
+                lines.push(index + 1);
+            }
+        }
+    }
+
+    refs
+}
+
+/// The `${NAME}` form of a `$NAME` variable
+fn braced(var: &str) -> String {
+    ["${", &var[1..], "}"].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undefined_lists_variables_without_a_value() {
+        let input = "HOST: http://localhost\n\nGET $HOST/widgets\nX-Token: ${REQMD_TEST_UNSET}\n";
+        let vars = Variables::new(input);
+        let undefined = vars.undefined(input);
+
+        assert_eq!(undefined.len(), 1);
+        assert_eq!(undefined["$REQMD_TEST_UNSET"], vec![4]);
+    }
+
+    #[test]
+    fn names_can_have_dashes() {
+        let input = "API: http://wrong\nAPI-HOST: http://localhost\n\nGET $API-HOST/users\nX-Api: ${API}\n";
+        let vars = Variables::new(input);
+        let refs = references(input);
+
+        assert_eq!(refs.keys().collect::<Vec<_>>(), vec!["$API", "$API-HOST"]);
+        assert_eq!(vars.source_of("$API-HOST"), Some("file"));
+        assert!(vars.expand(input).contains("GET http://localhost/users"));
+    }
+
+    #[test]
+    fn undefined_skips_request_bodies() {
+        let input = "```\nPOST /schemas\nHost: http://localhost\nX-Token: $REQMD_TEST_UNSET\n```\n```json\n{\"$schema\": \"x\", \"$ref\": \"#/a\"}\n```\n";
+        let vars = Variables::new(input);
+        let undefined = vars.undefined(input);
+
+        assert_eq!(undefined.keys().collect::<Vec<_>>(), vec!["$REQMD_TEST_UNSET"]);
+    }
 }
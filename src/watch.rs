@@ -0,0 +1,37 @@
+use notify::{RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Calls `on_change` once up front and then again every time the file at
+/// `path` changes on disk, blocking forever.  Bursts of events from a single
+/// save are collapsed into one call.
+pub fn watch_file<F: FnMut()>(path: &Path, mut on_change: F) -> notify::Result<()> {
+    let path = path.canonicalize()?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    // Editors often save by replacing the file, so watch the directory
+    // it lives in rather than the file itself.
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    on_change();
+
+    loop {
+        let event = match rx.recv() {
+            Ok(event) => event?,
+            Err(_) => return Ok(()),
+        };
+
+        if event.kind.is_access() || !event.paths.iter().any(|p| p == &path) {
+            continue;
+        }
+
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+        on_change();
+    }
+}